@@ -12,8 +12,16 @@ const EXIT_HCP_SPAWN: i32 = 961;
 const EXIT_HCP_IO: i32 = 962;
 const EXIT_HCP_HTTP: i32 = 963;
 const EXIT_HCP_UNKNOWN: i32 = 964;
+const EXIT_HCP_TIMEOUT: i32 = 965;
 const TEE_MAX_BYTES: usize = 40_000;
 
+/// Result of waiting on the child process
+struct WaitOutcome {
+    status: std::process::ExitStatus,
+    /// Set when the child was killed because it ran past `--hcp-timeout`
+    timed_out: bool,
+}
+
 /// Trims everything after the last '\r' or '\n'
 fn trim_trailing(buf: &[u8]) -> &[u8] {
     buf.iter()
@@ -59,6 +67,149 @@ fn tee(mut rdr: impl std::io::Read, mut wrtr: impl std::io::Write, max_bytes: us
     Ok(tail.into())
 }
 
+/// Which of the child's output streams a captured line came from
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Stream {
+    Stdout,
+    Stderr,
+}
+
+impl Stream {
+    fn tag(self) -> &'static str {
+        match self {
+            Stream::Stdout => "out",
+            Stream::Stderr => "err",
+        }
+    }
+}
+
+/// A line of captured output along with when it arrived and which stream it came from, used to
+/// reconstruct the true interleaving of stdout/stderr in `--hcp-combined` mode
+type CombinedLine = (std::time::Instant, Stream, Vec<u8>);
+
+/// Shared state fed by both `tee_timestamped` readers: the captured lines plus a running byte
+/// count so the retained tail can be bounded continuously instead of only once at the end
+#[derive(Default)]
+struct CombinedBuf {
+    lines: VecDeque<CombinedLine>,
+    total_bytes: usize,
+}
+
+impl CombinedBuf {
+    /// Pushes a line and then drops the oldest whole lines (across either stream) until the buffer
+    /// is back within `max_bytes`. Unlike `tee`'s byte-wise tail, lines are never cut, so fewer
+    /// than `max_bytes` bytes may be kept and a single line longer than `max_bytes` is not kept.
+    fn push(&mut self, line: CombinedLine, max_bytes: usize) {
+        self.total_bytes += line.2.len();
+        self.lines.push_back(line);
+        while self.total_bytes > max_bytes {
+            match self.lines.pop_front() {
+                Some((_, _, dropped)) => self.total_bytes -= dropped.len(),
+                None => break,
+            }
+        }
+    }
+}
+
+/// Like `tee`, but instead of returning a trailing tail, pushes each completed line onto `sink`
+/// tagged with its arrival time and stream so a later merge step can interleave stdout/stderr in
+/// the order they actually happened. A single read can contain several lines, so `to_emit` is
+/// split on newlines and each is pushed (and tail-trimmed) individually. Still forwards to
+/// `wrtr` when tee-to-local is enabled.
+fn tee_timestamped(
+    mut rdr: impl std::io::Read,
+    mut wrtr: Option<impl std::io::Write>,
+    stream: Stream,
+    sink: std::sync::Arc<std::sync::Mutex<CombinedBuf>>,
+    max_bytes: usize,
+) -> std::io::Result<()> {
+    let mut write_buf = Vec::new();
+    let mut buf = [0; 16 * 1024];
+    loop {
+        match rdr.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => {
+                write_buf.extend_from_slice(&buf[..n]);
+                let to_emit = trim_trailing(&write_buf);
+                if !to_emit.is_empty() {
+                    if let Some(wrtr) = wrtr.as_mut() {
+                        wrtr.write_all(to_emit)?;
+                    }
+                    let now = std::time::Instant::now();
+                    let mut sink = sink.lock().unwrap();
+                    for line in to_emit.lines_with_terminator() {
+                        sink.push((now, stream, line.to_vec()), max_bytes);
+                    }
+                    drop(sink);
+                    let n_emitted = to_emit.len();
+                    write_buf.drain(..n_emitted);
+                }
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    if !write_buf.is_empty() {
+        if let Some(wrtr) = wrtr.as_mut() {
+            wrtr.write_all(&write_buf)?;
+        }
+        let now = std::time::Instant::now();
+        let mut sink = sink.lock().unwrap();
+        for line in write_buf.lines_with_terminator() {
+            sink.push((now, stream, line.to_vec()), max_bytes);
+        }
+    }
+    Ok(())
+}
+
+/// Merges timestamped lines from both streams into a single buffer, in the order they arrived,
+/// optionally prefixing each line with a relative timestamp and stream tag
+fn merge_combined(mut lines: Vec<CombinedLine>, start: std::time::Instant, tag_lines: bool, max_bytes: usize) -> Vec<u8> {
+    lines.sort_by_key(|(time, _, _)| *time);
+    let mut rendered: VecDeque<Vec<u8>> = lines
+        .iter()
+        .map(|(time, stream, line)| {
+            if !tag_lines {
+                return line.clone();
+            }
+            let elapsed = time.saturating_duration_since(start).as_secs_f64();
+            let mut rendered_line = format!("[+{:.3}s][{}] ", elapsed, stream.tag()).into_bytes();
+            rendered_line.extend_from_slice(line);
+            rendered_line
+        })
+        .collect();
+    // Tagging can add substantially more bytes than the lines it's attached to (tag overhead
+    // scales with line *count*, not byte count), so `CombinedBuf::push`'s continuous per-line
+    // cap isn't enough to guarantee the tagged output fits `max_bytes`. Drop whole leading lines
+    // (never chop mid-line/mid-tag) until it does.
+    let mut total_bytes: usize = rendered.iter().map(Vec::len).sum();
+    while total_bytes > max_bytes {
+        match rendered.pop_front() {
+            Some(dropped) => total_bytes -= dropped.len(),
+            None => break,
+        }
+    }
+    rendered.into_iter().flatten().collect()
+}
+
+/// The captured output of the child process, either kept per-stream or merged into a single
+/// chronologically ordered stream when `--hcp-combined` is set
+enum Output {
+    Separate { out: Vec<u8>, err: Vec<u8> },
+    Combined(Vec<u8>),
+}
+
+/// If the child was killed by `--hcp-timeout`, prefixes `msg` with "Command timed out after Ns"
+/// and forces a non-zero `code`, since a timeout is always a failure regardless of
+/// `--hcp-ignore-code`. No-op when `timed_out` is false.
+fn apply_timeout(msg: String, code: i32, timed_out: bool, timeout: Option<Duration>) -> (String, i32) {
+    if !timed_out {
+        return (msg, code);
+    }
+    let msg = format!("Command timed out after {}s\n{}", timeout.unwrap_or_default().as_secs(), msg);
+    let code = if code == 0 { EXIT_HCP_TIMEOUT } else { code };
+    (msg, code)
+}
+
 /// Run a subprocess and ping healthchecks.io with the result
 #[derive(Parser)]
 #[command(name = "hcp", version, trailing_var_arg = true)]
@@ -75,6 +226,25 @@ struct Args {
     #[arg(long = "hcp-ignore-code", env = "HCP_IGNORE_CODE")]
     hcp_ignore_code: bool,
 
+    /// Maximum number of seconds to let cmd run before sending SIGTERM (CTRL_BREAK_EVENT on
+    /// Windows). Unbounded if unset
+    #[arg(long = "hcp-timeout", env = "HCP_TIMEOUT")]
+    hcp_timeout: Option<u64>,
+
+    /// Number of seconds to wait after SIGTERM before sending SIGKILL (terminating cmd's whole
+    /// job object on Windows)
+    #[arg(long = "hcp-kill-timeout", env = "HCP_KILL_TIMEOUT", default_value_t = 5)]
+    hcp_kill_timeout: u64,
+
+    /// Merge cmd's stdout and stderr into a single chronologically ordered stream
+    #[arg(long = "hcp-combined", env = "HCP_COMBINED")]
+    hcp_combined: bool,
+
+    /// With --hcp-combined, prefix each line with a relative timestamp and stream tag, e.g.
+    /// "[+1.234s][out]"
+    #[arg(long = "hcp-combined-tags", env = "HCP_COMBINED_TAGS")]
+    hcp_combined_tags: bool,
+
     /// Command and arguments to run
     #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
     cmd: Vec<OsString>,
@@ -251,55 +421,309 @@ use internal::HealthCheck;
 
 #[cfg(unix)]
 mod signal {
+    use super::WaitOutcome;
+    use std::os::unix::io::RawFd;
     use std::sync::atomic::{AtomicI32, Ordering};
+    use std::time::{Duration, Instant};
 
-    pub static SIGNAL_RECEIVED: AtomicI32 = AtomicI32::new(0);
+    /// Write end of the self-pipe. Set once by `install_handlers` and read only from the
+    /// async-signal-safe handler, so a plain relaxed store/load is enough.
+    static WRITE_FD: AtomicI32 = AtomicI32::new(-1);
 
+    /// Async-signal-safe: writes the signal number as a single byte into the self-pipe so the
+    /// wait loop can pick it up from `libc::poll`. No allocation, no locking.
     extern "C" fn handler(sig: libc::c_int) {
-        SIGNAL_RECEIVED.store(sig, Ordering::SeqCst);
+        let fd = WRITE_FD.load(Ordering::Relaxed);
+        if fd >= 0 {
+            let byte = sig as u8;
+            unsafe {
+                libc::write(fd, &byte as *const u8 as *const libc::c_void, 1);
+            }
+        }
     }
 
-    pub fn install_handlers() {
+    fn set_nonblocking_cloexec(fd: RawFd) {
+        unsafe {
+            let flags = libc::fcntl(fd, libc::F_GETFL);
+            libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK);
+            let fd_flags = libc::fcntl(fd, libc::F_GETFD);
+            libc::fcntl(fd, libc::F_SETFD, fd_flags | libc::FD_CLOEXEC);
+        }
+    }
+
+    /// Creates the self-pipe, installs handlers for SIGTERM/SIGINT/SIGCHLD that write into it,
+    /// and returns the pipe's read end for the wait loop to poll on.
+    pub fn install_handlers() -> RawFd {
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+            panic!("Failed to create self-pipe: {}", std::io::Error::last_os_error());
+        }
+        let [read_fd, write_fd] = fds;
+        set_nonblocking_cloexec(read_fd);
+        set_nonblocking_cloexec(write_fd);
+        WRITE_FD.store(write_fd, Ordering::Relaxed);
         unsafe {
             libc::signal(libc::SIGTERM, handler as libc::sighandler_t);
             libc::signal(libc::SIGINT, handler as libc::sighandler_t);
+            libc::signal(libc::SIGCHLD, handler as libc::sighandler_t);
         }
+        read_fd
     }
 
-    pub fn check_and_forward(child_pid: u32) {
-        let sig = SIGNAL_RECEIVED.swap(0, Ordering::SeqCst);
-        if sig != 0 {
-            unsafe {
-                libc::kill(child_pid as libc::pid_t, sig);
+    /// Drains the self-pipe, reading until it's empty (`EAGAIN`), and returns the last
+    /// SIGTERM/SIGINT seen, if any. SIGCHLD bytes just wake the poll and are otherwise ignored.
+    fn drain_pipe(read_fd: RawFd) -> Option<libc::c_int> {
+        let mut buf = [0u8; 64];
+        let mut forwardable = None;
+        loop {
+            let n = unsafe { libc::read(read_fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+            if n <= 0 {
+                break;
+            }
+            for &b in &buf[..n as usize] {
+                let sig = b as libc::c_int;
+                if sig == libc::SIGTERM || sig == libc::SIGINT {
+                    forwardable = Some(sig);
+                }
             }
         }
+        forwardable
     }
 
-    pub fn wait_or_kill(child: &mut std::process::Child) -> std::io::Result<std::process::ExitStatus> {
+    /// Waits for `child` to exit, forwarding any SIGTERM/SIGINT received by this process. Blocks
+    /// in `libc::poll` on the self-pipe `read_fd` instead of polling `try_wait` on a fixed
+    /// cadence, so forwarding is near-instant and there's no wakeup when nothing is happening.
+    /// If `timeout` elapses before the child exits, sends SIGTERM to the child; if the child is
+    /// still running after an additional `kill_timeout`, sends SIGKILL.
+    pub fn wait_or_kill(
+        child: &mut std::process::Child,
+        read_fd: RawFd,
+        timeout: Option<Duration>,
+        kill_timeout: Duration,
+    ) -> std::io::Result<WaitOutcome> {
         let pid = child.id();
-        // Check for pending signal before entering wait loop
-        check_and_forward(pid);
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut kill_deadline = None;
+        let mut timed_out = false;
+        let mut killed = false;
 
-        // Try waiting with periodic signal checks
         loop {
-            match child.try_wait()? {
-                Some(status) => return Ok(status),
-                None => {
-                    check_and_forward(pid);
-                    std::thread::sleep(std::time::Duration::from_millis(50));
+            if let Some(status) = child.try_wait()? {
+                return Ok(WaitOutcome { status, timed_out });
+            }
+
+            let now = Instant::now();
+            if let Some(kd) = kill_deadline {
+                if !killed && now >= kd {
+                    killed = true;
+                    unsafe {
+                        libc::kill(pid as libc::pid_t, libc::SIGKILL);
+                    }
+                }
+            } else if let Some(d) = deadline {
+                if now >= d {
+                    timed_out = true;
+                    unsafe {
+                        libc::kill(pid as libc::pid_t, libc::SIGTERM);
+                    }
+                    kill_deadline = Some(now + kill_timeout);
+                }
+            }
+
+            // Once SIGKILL has been sent there's nothing left to schedule; block until
+            // SIGCHLD wakes us to reap the child instead of spinning at a 0ms timeout.
+            // Otherwise bound the poll by whichever deadline is next, if any are active.
+            let poll_timeout_ms = if killed {
+                -1
+            } else {
+                match kill_deadline.or(deadline) {
+                    Some(d) => {
+                        let now = Instant::now();
+                        if now >= d {
+                            0
+                        } else {
+                            (d - now).as_millis().min(libc::c_int::MAX as u128) as libc::c_int
+                        }
+                    }
+                    None => -1,
+                }
+            };
+
+            let mut pollfd = libc::pollfd {
+                fd: read_fd,
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let rv = unsafe { libc::poll(&mut pollfd, 1, poll_timeout_ms) };
+            if rv > 0 && pollfd.revents & libc::POLLIN != 0 {
+                if let Some(sig) = drain_pipe(read_fd) {
+                    unsafe {
+                        libc::kill(pid as libc::pid_t, sig);
+                    }
+                }
+            }
+            // Loop back to try_wait(), whether we woke on SIGCHLD, a forwarded signal,
+            // a poll timeout, or (harmlessly) a spurious wakeup.
+        }
+    }
+}
+
+// Requires the `windows-sys` crate as a `cfg(windows)` dependency with the
+// `Win32_Foundation`, `Win32_System_Console`, `Win32_System_JobObjects`, and `Win32_System_Threading`
+// features enabled (this tree has no Cargo.toml to declare it in, so it's noted here instead).
+#[cfg(windows)]
+mod signal {
+    use super::WaitOutcome;
+    use std::os::windows::io::AsRawHandle;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::time::{Duration, Instant};
+    use windows_sys::Win32::Foundation::{CloseHandle, BOOL, HANDLE};
+    use windows_sys::Win32::System::Console::{
+        GenerateConsoleCtrlEvent, SetConsoleCtrlHandler, CTRL_BREAK_EVENT, CTRL_C_EVENT,
+    };
+    use windows_sys::Win32::System::JobObjects::{
+        AssignProcessToJobObject, CreateJobObjectW, SetInformationJobObject,
+        JobObjectExtendedLimitInformation, TerminateJobObject,
+        JOBOBJECT_EXTENDED_LIMIT_INFORMATION, JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE,
+    };
+
+    /// Set by `ctrl_handler` to the event type plus one when Ctrl+C/Ctrl+Break arrives (so that
+    /// CTRL_C_EVENT, which is 0, is still recorded); 0 means none pending.
+    static CTRL_EVENT: AtomicU32 = AtomicU32::new(0);
+
+    unsafe extern "system" fn ctrl_handler(ctrl_type: u32) -> BOOL {
+        match ctrl_type {
+            CTRL_C_EVENT | CTRL_BREAK_EVENT => {
+                CTRL_EVENT.store(ctrl_type + 1, Ordering::SeqCst);
+                // Tell Windows we handled it so it doesn't tear this process down before
+                // we've had a chance to forward the event and wait on the child.
+                1
+            }
+            _ => 0,
+        }
+    }
+
+    pub fn install_handlers() {
+        unsafe {
+            SetConsoleCtrlHandler(Some(ctrl_handler), 1);
+        }
+    }
+
+    /// Creates a Job Object with `JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE` and assigns `child` to it,
+    /// so the whole process tree is torn down together instead of orphaning grandchildren.
+    /// Panics if any step fails, since a Job Object we can't configure defeats the point of
+    /// calling this in the first place (mirrors the unix side panicking if the self-pipe can't
+    /// be created).
+    fn assign_to_job(child: &std::process::Child) -> HANDLE {
+        unsafe {
+            let job = CreateJobObjectW(std::ptr::null(), std::ptr::null());
+            if job == 0 {
+                panic!("Failed to create Job Object: {}", std::io::Error::last_os_error());
+            }
+            let mut info: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = std::mem::zeroed();
+            info.BasicLimitInformation.LimitFlags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+            if SetInformationJobObject(
+                job,
+                JobObjectExtendedLimitInformation,
+                &info as *const _ as *const _,
+                std::mem::size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as u32,
+            ) == 0
+            {
+                panic!("Failed to configure Job Object: {}", std::io::Error::last_os_error());
+            }
+            if AssignProcessToJobObject(job, child.as_raw_handle() as HANDLE) == 0 {
+                panic!(
+                    "Failed to assign child process to Job Object: {}",
+                    std::io::Error::last_os_error()
+                );
+            }
+            job
+        }
+    }
+
+    /// Waits for `child` to exit, forwarding the first CTRL_C/CTRL_BREAK this process receives to
+    /// the child's process group as a CTRL_BREAK_EVENT (the only one of the two that Win32 allows
+    /// targeting at a specific group); later events are consumed by `ctrl_handler` and not
+    /// forwarded. If `timeout` elapses before the child exits, sends a graceful CTRL_BREAK_EVENT
+    /// itself unless one was already forwarded; if the child is still running after an
+    /// additional `kill_timeout`, calls `TerminateJobObject` to take down the whole job.
+    ///
+    /// Requires `child` to have been spawned with `CREATE_NEW_PROCESS_GROUP` so its process
+    /// group id equals its pid; otherwise there is no distinct group to target and events would
+    /// have to go to group 0 (every process on hcp's console, including hcp itself).
+    pub fn wait_or_kill(
+        child: &mut std::process::Child,
+        timeout: Option<Duration>,
+        kill_timeout: Duration,
+    ) -> std::io::Result<WaitOutcome> {
+        let job = assign_to_job(child);
+        let child_group = child.id();
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut kill_deadline = None;
+        let mut timed_out = false;
+        let mut forwarded = false;
+
+        let outcome = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+
+            let ctrl = CTRL_EVENT.swap(0, Ordering::SeqCst);
+            if ctrl != 0 && !forwarded {
+                forwarded = true;
+                // CTRL_C_EVENT can't be targeted at a nonzero process-group id (Win32 requires
+                // group 0 for that), and the child's group has Ctrl+C delivery disabled anyway
+                // since it was created with CREATE_NEW_PROCESS_GROUP. CTRL_BREAK_EVENT is the one
+                // event that *can* be targeted at a specific group, so forward that regardless of
+                // which of the two this process itself received.
+                unsafe {
+                    GenerateConsoleCtrlEvent(CTRL_BREAK_EVENT, child_group);
                 }
             }
+
+            let now = Instant::now();
+            if let Some(kd) = kill_deadline {
+                if now >= kd {
+                    unsafe {
+                        TerminateJobObject(job, 1);
+                    }
+                }
+            } else if let Some(d) = deadline {
+                if now >= d {
+                    timed_out = true;
+                    if !forwarded {
+                        forwarded = true;
+                        unsafe {
+                            GenerateConsoleCtrlEvent(CTRL_BREAK_EVENT, child_group);
+                        }
+                    }
+                    kill_deadline = Some(now + kill_timeout);
+                }
+            }
+
+            std::thread::sleep(Duration::from_millis(50));
+        };
+
+        unsafe {
+            CloseHandle(job);
         }
+        Ok(WaitOutcome { status: outcome, timed_out })
     }
 }
 
 fn main() {
     #[cfg(unix)]
+    let signal_read_fd = signal::install_handlers();
+    #[cfg(windows)]
     signal::install_handlers();
 
     let args = Args::parse();
     let tee_output = args.hcp_tee;
     let ignore_code = args.hcp_ignore_code;
+    let hcp_timeout = args.hcp_timeout.map(Duration::from_secs);
+    let hcp_kill_timeout = Duration::from_secs(args.hcp_kill_timeout);
+    let combined = args.hcp_combined;
+    let combined_tags = args.hcp_combined_tags;
     let hc = match args.hcp_id.as_deref() {
         Some(hcp_id) => match HealthCheck::from_str(hcp_id) {
             Some(hc) => hc,
@@ -319,16 +743,28 @@ fn main() {
         None => hc.finish_and_exit("No command given", 0, true),
     };
     hc.start();
-    let mut proc = match Command::new(cmd)
+    let mut command = Command::new(cmd);
+    command
         .args(cmd_args)
         .env_remove("HCP_ID")
         .env_remove("HCP_TEE")
         .env_remove("HCP_IGNORE_CODE")
+        .env_remove("HCP_TIMEOUT")
+        .env_remove("HCP_KILL_TIMEOUT")
+        .env_remove("HCP_COMBINED")
+        .env_remove("HCP_COMBINED_TAGS")
         .stdin(Stdio::inherit())
         .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
+        .stderr(Stdio::piped());
+    #[cfg(windows)]
     {
+        // Gives the child its own process group (group id == child pid) so console control
+        // events can be targeted at it specifically instead of at group 0 (every process on
+        // hcp's console, including hcp itself).
+        use std::os::windows::process::CommandExt;
+        command.creation_flags(windows_sys::Win32::System::Threading::CREATE_NEW_PROCESS_GROUP);
+    }
+    let mut proc = match command.spawn() {
         Ok(p) => p,
         Err(e) => hc.finish_and_exit(&format!("Failed to spawn process: {}", e), EXIT_HCP_SPAWN, true),
     };
@@ -347,30 +783,41 @@ fn main() {
         None
     };
 
-    // Spawn threads for continuously reading from the child process's stdout and stderr. If
-    // tee_output is enabled forward the output to the processes pipes
-    let stdout_thread = std::thread::spawn(move || {
-        if let Some(pipe_stdout) = pipe_stdout {
-            tee(child_stdout, pipe_stdout, TEE_MAX_BYTES)
-        } else {
-            tee(child_stdout, std::io::sink(), TEE_MAX_BYTES)
-        }
-    });
-    let stderr_thread = std::thread::spawn(move || {
-        if let Some(pipe_stderr) = pipe_stderr {
-            tee(child_stderr, pipe_stderr, TEE_MAX_BYTES)
-        } else {
-            tee(child_stderr, std::io::sink(), TEE_MAX_BYTES)
-        }
-    });
+    let start_time = std::time::Instant::now();
+    // Shared only in --hcp-combined mode, so the two readers can interleave lines in arrival
+    // order instead of each accumulating its own detached buffer
+    let combined_lines: std::sync::Arc<std::sync::Mutex<CombinedBuf>> = Default::default();
+
+    // Spawn threads for continuously reading from the child process's stdout and stderr. Both
+    // threads must keep draining their pipe to avoid deadlocking the child. If tee_output is
+    // enabled forward the output to the processes pipes
+    let stdout_thread = if combined {
+        let sink = combined_lines.clone();
+        std::thread::spawn(move || tee_timestamped(child_stdout, pipe_stdout, Stream::Stdout, sink, TEE_MAX_BYTES).map(|()| Vec::new()))
+    } else {
+        std::thread::spawn(move || match pipe_stdout {
+            Some(pipe_stdout) => tee(child_stdout, pipe_stdout, TEE_MAX_BYTES),
+            None => tee(child_stdout, std::io::sink(), TEE_MAX_BYTES),
+        })
+    };
+    let stderr_thread = if combined {
+        let sink = combined_lines.clone();
+        std::thread::spawn(move || tee_timestamped(child_stderr, pipe_stderr, Stream::Stderr, sink, TEE_MAX_BYTES).map(|()| Vec::new()))
+    } else {
+        std::thread::spawn(move || match pipe_stderr {
+            Some(pipe_stderr) => tee(child_stderr, pipe_stderr, TEE_MAX_BYTES),
+            None => tee(child_stderr, std::io::sink(), TEE_MAX_BYTES),
+        })
+    };
 
     #[cfg(unix)]
-    let wait_result = signal::wait_or_kill(&mut proc);
-    #[cfg(not(unix))]
-    let wait_result = proc.wait();
+    let wait_result = signal::wait_or_kill(&mut proc, signal_read_fd, hcp_timeout, hcp_kill_timeout);
+    #[cfg(windows)]
+    let wait_result = signal::wait_or_kill(&mut proc, hcp_timeout, hcp_kill_timeout);
 
     match wait_result {
-        Ok(status) => {
+        Ok(outcome) => {
+            let status = outcome.status;
             let out = match stdout_thread.join() {
                 Ok(Ok(out)) => out,
                 Ok(Err(e)) => hc.finish_and_exit(
@@ -389,8 +836,16 @@ fn main() {
                 ),
                 Err(e) => std::panic::resume_unwind(e),
             };
+            let output = if combined {
+                let buf = std::sync::Arc::try_unwrap(combined_lines)
+                    .map(|m| m.into_inner().unwrap())
+                    .unwrap_or_default();
+                Output::Combined(merge_combined(Vec::from(buf.lines), start_time, combined_tags, TEE_MAX_BYTES))
+            } else {
+                Output::Separate { out, err }
+            };
             let mut msg = String::new();
-            let mut code = match status.code() {
+            let code = match status.code() {
                 Some(code) => {
                     if let Err(e) = writeln!(msg, "Command exited with exit code {}", code) {
                         eprintln!("Write to message buffer failed: {}", e)
@@ -402,18 +857,29 @@ fn main() {
                     EXIT_HCP_UNKNOWN
                 }
             };
-            if !out.is_empty() {
-                let _ = writeln!(msg, "stdout:");
-                let _ = writeln!(msg, "{}", out.as_bstr());
-            }
-            if !err.is_empty() {
-                if !out.is_empty() {
-                    let _ = writeln!(msg);
+            match &output {
+                Output::Separate { out, err } => {
+                    if !out.is_empty() {
+                        let _ = writeln!(msg, "stdout:");
+                        let _ = writeln!(msg, "{}", out.as_bstr());
+                    }
+                    if !err.is_empty() {
+                        if !out.is_empty() {
+                            let _ = writeln!(msg);
+                        }
+                        let _ = writeln!(msg, "stderr:");
+                        let _ = writeln!(msg, "{}", err.as_bstr());
+                    }
+                }
+                Output::Combined(lines) => {
+                    if !lines.is_empty() {
+                        let _ = writeln!(msg, "output:");
+                        let _ = writeln!(msg, "{}", lines.as_bstr());
+                    }
                 }
-                let _ = writeln!(msg, "stderr:");
-                let _ = writeln!(msg, "{}", err.as_bstr());
             }
-            if ignore_code {
+            let (msg, mut code) = apply_timeout(msg, code, outcome.timed_out, hcp_timeout);
+            if !outcome.timed_out && ignore_code {
                 // 0 would indicate success
                 code = 0;
             }
@@ -465,4 +931,87 @@ mod test {
         assert_eq!(out_returned.len(), TEE_MAX_BYTES);
         assert_eq!(out_returned, &input[size - TEE_MAX_BYTES..]);
     }
+
+    #[test]
+    fn test_merge_combined_orders_by_arrival_time() {
+        let start = std::time::Instant::now();
+        let t0 = start;
+        let t1 = start + Duration::from_millis(1);
+        let t2 = start + Duration::from_millis(2);
+        // Fed out of order to make sure the merge sorts rather than trusting input order
+        let lines = vec![
+            (t2, Stream::Stdout, b"third\n".to_vec()),
+            (t0, Stream::Stderr, b"first\n".to_vec()),
+            (t1, Stream::Stdout, b"second\n".to_vec()),
+        ];
+        let out = merge_combined(lines, start, false, TEE_MAX_BYTES);
+        assert_eq!(out, b"first\nsecond\nthird\n");
+    }
+
+    #[test]
+    fn test_merge_combined_tags_are_optional() {
+        let start = std::time::Instant::now();
+        let lines = vec![(start, Stream::Stdout, b"hi\n".to_vec())];
+        assert_eq!(merge_combined(lines.clone(), start, false, TEE_MAX_BYTES), b"hi\n");
+        let tagged = merge_combined(lines, start, true, TEE_MAX_BYTES);
+        assert_eq!(tagged, b"[+0.000s][out] hi\n");
+    }
+
+    #[test]
+    fn test_merge_combined_caps_to_tail() {
+        let start = std::time::Instant::now();
+        let lines = vec![
+            (start, Stream::Stdout, b"aaaa".to_vec()),
+            (start, Stream::Stdout, b"bbbb".to_vec()),
+        ];
+        let out = merge_combined(lines, start, false, 4);
+        assert_eq!(out, b"bbbb");
+    }
+
+    #[test]
+    fn test_merge_combined_tagged_cap_drops_whole_lines() {
+        // With tags on, the rendered bytes are dominated by tag overhead rather than line
+        // content, so a byte-wise chop of the cap would land mid-tag. Capping to a budget that
+        // only fits the last line's tag+content should drop the first line whole, never a
+        // truncated tag.
+        let start = std::time::Instant::now();
+        let lines = vec![
+            (start, Stream::Stdout, b"a\n".to_vec()),
+            (start, Stream::Stdout, b"b\n".to_vec()),
+        ];
+        let last_line_tagged = b"[+0.000s][out] b\n".to_vec();
+        let out = merge_combined(lines, start, true, last_line_tagged.len());
+        assert_eq!(out, last_line_tagged);
+    }
+
+    #[test]
+    fn test_combined_buf_push_bounds_total_bytes() {
+        let mut buf = CombinedBuf::default();
+        let now = std::time::Instant::now();
+        for _ in 0..10 {
+            buf.push((now, Stream::Stdout, b"abcd".to_vec()), 10);
+        }
+        assert!(buf.total_bytes <= 10);
+        let retained: usize = buf.lines.iter().map(|(_, _, line)| line.len()).sum();
+        assert_eq!(retained, buf.total_bytes);
+    }
+
+    #[test]
+    fn test_apply_timeout_forces_nonzero_code() {
+        let (msg, code) = apply_timeout("ok\n".to_string(), 0, true, Some(Duration::from_secs(5)));
+        assert_eq!(code, EXIT_HCP_TIMEOUT);
+        assert!(msg.starts_with("Command timed out after 5s\n"));
+        assert!(msg.ends_with("ok\n"));
+
+        // A non-zero exit code from the child is preserved rather than overwritten
+        let (_, code) = apply_timeout("boom\n".to_string(), 42, true, Some(Duration::from_secs(1)));
+        assert_eq!(code, 42);
+    }
+
+    #[test]
+    fn test_apply_timeout_is_noop_when_not_timed_out() {
+        let (msg, code) = apply_timeout("ok\n".to_string(), 0, false, None);
+        assert_eq!(msg, "ok\n");
+        assert_eq!(code, 0);
+    }
 }